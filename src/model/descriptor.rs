@@ -0,0 +1,209 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime Sarl, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Lowering of [`WalletTemplate`] spending conditions into concrete
+//! miniscript policies and output descriptors.
+
+use std::fmt;
+
+use bitcoin::PublicKey;
+use miniscript::descriptor::{Descriptor, TapTree, Tr, Wsh};
+use miniscript::policy::concrete::Policy as ConcretePolicy;
+use miniscript::{Miniscript, Segwitv0, Tap};
+
+use super::{SigsReq, TimelockReq, WalletFormat, WalletTemplate};
+
+/// The standard "nothing-up-my-sleeve" point used as an unspendable Taproot
+/// internal key for templates that have no natural key-path spend (every
+/// condition is realized as a script-path tapleaf instead; see
+/// `to_taproot_tree` for the per-leaf builder).
+pub const NUMS_INTERNAL_KEY: &str =
+    "0250929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// Error returned when a [`WalletTemplate`] cannot be compiled into a
+/// concrete policy or descriptor.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DescriptorError {
+    /// the template has no spending conditions to compile.
+    NoConditions,
+    /// the number of signer keys does not fit the template's
+    /// `min_signer_count`/`max_signer_count` bounds.
+    SignerCountMismatch,
+    /// miniscript failed to compile the policy into a valid script.
+    Compilation(String),
+    /// a condition's [`TimelockReq::AfterTime`] cannot be represented as
+    /// `nLockTime`: the timestamp is before the BIP65 height/time threshold
+    /// or beyond what a 32-bit Unix time can hold (year 2106).
+    InvalidTimelockValue,
+    /// the descriptor string could not be parsed.
+    ParseFailure(String),
+    /// the descriptor is neither a `wsh(...)` nor a `tr(...)` descriptor.
+    UnsupportedDescriptor,
+    /// the descriptor's policy does not have the shape `to_policy` can
+    /// produce (a threshold, optionally timelocked, `or`-ed with siblings).
+    UnsupportedPolicy(String),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DescriptorError::NoConditions => {
+                f.write_str("wallet template has no spending conditions to compile")
+            }
+            DescriptorError::SignerCountMismatch => f.write_str(
+                "number of signer keys does not match the template's signer count bounds",
+            ),
+            DescriptorError::Compilation(msg) => {
+                write!(f, "failed to compile wallet template into a descriptor: {}", msg)
+            }
+            DescriptorError::InvalidTimelockValue => f.write_str(
+                "an AfterTime timelock falls outside the range representable by nLockTime \
+                 (before 1985-11-05T00:53:20Z or after 2106-02-07T06:28:15Z)",
+            ),
+            DescriptorError::ParseFailure(msg) => {
+                write!(f, "failed to parse descriptor: {}", msg)
+            }
+            DescriptorError::UnsupportedDescriptor => {
+                f.write_str("only wsh(...) and tr(...) descriptors can be imported into a wallet template")
+            }
+            DescriptorError::UnsupportedPolicy(msg) => {
+                write!(f, "descriptor policy is not representable as a wallet template: {}", msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DescriptorError {}
+
+impl WalletTemplate {
+    /// Lowers a single [`SpendingCondition`]'s signature threshold into a
+    /// `thresh(n, pk(key), ...)` policy fragment over `signers`.
+    pub(super) fn sigs_policy(sigs: SigsReq, signers: &[PublicKey]) -> ConcretePolicy<PublicKey> {
+        let keys = signers.iter().cloned().map(ConcretePolicy::Key).collect();
+        let threshold = match sigs {
+            SigsReq::All => signers.len(),
+            SigsReq::Any => 1,
+            SigsReq::AtLeast(k) => k as usize,
+        };
+        ConcretePolicy::Threshold(threshold, keys)
+    }
+
+    /// Conjoins a signature policy with the `after(...)`/`older(...)`
+    /// fragment demanded by `timelock`, if any.
+    ///
+    /// Fails with [`DescriptorError::InvalidTimelockValue`] if `timelock` is
+    /// an [`TimelockReq::AfterTime`] whose timestamp cannot be represented
+    /// as `nLockTime` (see [`TimelockReq::as_locktime`]).
+    pub(super) fn timelocked_policy(
+        sigs_policy: ConcretePolicy<PublicKey>,
+        timelock: TimelockReq,
+    ) -> Result<ConcretePolicy<PublicKey>, DescriptorError> {
+        Ok(match timelock {
+            TimelockReq::Anytime => sigs_policy,
+            TimelockReq::AfterHeight(_) | TimelockReq::AfterTime(_) => {
+                let locktime = timelock.as_locktime().ok_or(DescriptorError::InvalidTimelockValue)?;
+                ConcretePolicy::And(vec![sigs_policy, ConcretePolicy::After(locktime)])
+            }
+            TimelockReq::AfterBlocks(_) | TimelockReq::AfterPeriod(_) => {
+                let sequence = timelock
+                    .as_sequence()
+                    .expect("relative TimelockReq variant always yields a sequence value");
+                ConcretePolicy::And(vec![sigs_policy, ConcretePolicy::Older(sequence)])
+            }
+        })
+    }
+
+    /// Compiles the template's spending conditions into a concrete
+    /// miniscript policy over `signers`.
+    ///
+    /// Each condition's [`SigsReq`] becomes a `thresh(n, ...)` fragment,
+    /// conjoined (`and`) with an `after(...)`/`older(...)` fragment when the
+    /// condition carries a timelock. Conditions are then combined with
+    /// `or`, weighted so that earlier, less-restricted conditions (the ones
+    /// spent from day to day) are favoured by the compiler over the
+    /// deeper, timelocked recovery conditions.
+    pub fn to_policy(
+        &self,
+        signers: &[PublicKey],
+    ) -> Result<ConcretePolicy<PublicKey>, DescriptorError> {
+        if self.conditions.is_empty() {
+            return Err(DescriptorError::NoConditions);
+        }
+        let min = self.min_signer_count.unwrap_or(1) as usize;
+        let max = self.max_signer_count.map(|m| m as usize).unwrap_or(signers.len());
+        if signers.len() < min || signers.len() > max {
+            return Err(DescriptorError::SignerCountMismatch);
+        }
+
+        let condition_count = self.conditions.len();
+        let mut branches: Vec<(usize, ConcretePolicy<PublicKey>)> = self
+            .conditions
+            .iter()
+            .enumerate()
+            .map(|(index, condition)| {
+                let sigs_policy = Self::sigs_policy(condition.sigs, signers);
+                let policy = Self::timelocked_policy(sigs_policy, condition.timelock)?;
+                Ok((condition_count - index, policy))
+            })
+            .collect::<Result<Vec<_>, DescriptorError>>()?;
+
+        Ok(if branches.len() == 1 {
+            branches.pop().expect("checked len == 1").1
+        } else {
+            ConcretePolicy::Or(branches)
+        })
+    }
+
+    /// Compiles the template into an output descriptor: a `wsh(...)`
+    /// descriptor for [`WalletFormat::Bip43`] formats, or a flattened
+    /// single-script `tr(...)` descriptor for [`WalletFormat::Taproot`].
+    ///
+    /// For a Taproot template with multiple conditions, prefer
+    /// [`WalletTemplate::to_taproot_tree`], which gives each condition its
+    /// own tapleaf instead of flattening them into one script.
+    pub fn to_descriptor(&self, signers: &[PublicKey]) -> Result<Descriptor<PublicKey>, DescriptorError> {
+        let policy = self.to_policy(signers)?;
+        match self.format {
+            WalletFormat::Bip43(_) => {
+                let ms: Miniscript<PublicKey, Segwitv0> = policy
+                    .compile()
+                    .map_err(|e| DescriptorError::Compilation(e.to_string()))?;
+                let wsh = Wsh::new(ms).map_err(|e| DescriptorError::Compilation(e.to_string()))?;
+                Ok(Descriptor::Wsh(wsh))
+            }
+            WalletFormat::Taproot => {
+                let ms: Miniscript<PublicKey, Tap> = policy
+                    .compile()
+                    .map_err(|e| DescriptorError::Compilation(e.to_string()))?;
+                let internal_key = NUMS_INTERNAL_KEY
+                    .parse()
+                    .expect("NUMS_INTERNAL_KEY is a valid compressed public key");
+                let tr = Tr::new(internal_key, Some(TapTree::Leaf(ms.into())))
+                    .map_err(|e| DescriptorError::Compilation(e.to_string()))?;
+                Ok(Descriptor::Tr(tr))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::PublicKey;
+
+    use super::NUMS_INTERNAL_KEY;
+
+    #[test]
+    fn nums_internal_key_parses() {
+        NUMS_INTERNAL_KEY
+            .parse::<PublicKey>()
+            .expect("NUMS_INTERNAL_KEY must be a valid compressed public key");
+    }
+}
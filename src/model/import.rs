@@ -0,0 +1,249 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime Sarl, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Reconstructing a [`WalletTemplate`] from an existing output descriptor:
+//! the inverse of [`super::descriptor::WalletTemplate::to_descriptor`].
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use bitcoin::PublicKey;
+use miniscript::descriptor::Descriptor;
+use miniscript::policy::semantic::Policy as SemanticPolicy;
+use miniscript::Liftable;
+
+use super::descriptor::{DescriptorError, NUMS_INTERNAL_KEY};
+use super::{Bip43, PublicNetwork, Requirement, SigsReq, SpendingCondition, TimelockReq, WalletFormat, WalletTemplate};
+
+/// Walks a lifted policy tree, turning a threshold of keys (optionally
+/// timelocked) into a single [`SpendingCondition`].
+///
+/// This is the inverse of
+/// `WalletTemplate::timelocked_policy(WalletTemplate::sigs_policy(...), ...)`:
+/// a bare `thresh(k, pk, ...)`/`multi(k, ...)` becomes a [`SigsReq`] with no
+/// timelock, and `and(thresh(...), after(...)|older(...))` becomes the same
+/// [`SigsReq`] gated behind the matching [`TimelockReq`].
+fn condition_from_policy(policy: &SemanticPolicy<PublicKey>) -> Result<SpendingCondition, DescriptorError> {
+    match policy {
+        SemanticPolicy::Key(_) => Ok(SpendingCondition { sigs: SigsReq::AtLeast(1), timelock: TimelockReq::Anytime }),
+        SemanticPolicy::Threshold(k, subs) => {
+            let sigs = sigs_req_from_threshold(*k, subs.len());
+            Ok(SpendingCondition { sigs, timelock: TimelockReq::Anytime })
+        }
+        SemanticPolicy::And(subs) if subs.len() == 2 => {
+            let (sigs_branch, timelock_branch) = match (&subs[0], &subs[1]) {
+                (sigs @ (SemanticPolicy::Key(_) | SemanticPolicy::Threshold(..)), tl) => (sigs, tl),
+                (tl, sigs @ (SemanticPolicy::Key(_) | SemanticPolicy::Threshold(..))) => (sigs, tl),
+                _ => {
+                    return Err(DescriptorError::UnsupportedPolicy(
+                        "expected `and(threshold, timelock)`".to_string(),
+                    ))
+                }
+            };
+            let sigs = match sigs_branch {
+                SemanticPolicy::Key(_) => sigs_req_from_threshold(1, 1),
+                SemanticPolicy::Threshold(k, subs) => sigs_req_from_threshold(*k, subs.len()),
+                _ => unreachable!("matched above"),
+            };
+            let timelock = match timelock_branch {
+                SemanticPolicy::After(height_or_time) => absolute_timelock(*height_or_time),
+                SemanticPolicy::Older(sequence) => relative_timelock(*sequence),
+                _ => {
+                    return Err(DescriptorError::UnsupportedPolicy(
+                        "expected an `after(...)`/`older(...)` fragment".to_string(),
+                    ))
+                }
+            };
+            Ok(SpendingCondition { sigs, timelock })
+        }
+        _ => Err(DescriptorError::UnsupportedPolicy(format!(
+            "cannot represent policy fragment {:?} as a single spending condition",
+            policy
+        ))),
+    }
+}
+
+fn sigs_req_from_threshold(k: usize, n: usize) -> SigsReq {
+    if k == n {
+        SigsReq::All
+    } else if k == 1 {
+        SigsReq::Any
+    } else {
+        SigsReq::AtLeast(k as u16)
+    }
+}
+
+/// Reconstructs the absolute [`TimelockReq`] matching an `after(...)`
+/// fragment's raw `nLockTime` value, per the BIP65 height/time threshold.
+fn absolute_timelock(locktime: u32) -> TimelockReq {
+    if locktime < super::LOCKTIME_THRESHOLD {
+        TimelockReq::AfterHeight(locktime)
+    } else {
+        use chrono::TimeZone;
+        TimelockReq::AfterTime(chrono::Utc.timestamp(locktime as i64, 0))
+    }
+}
+
+/// Reconstructs the relative [`TimelockReq`] matching an `older(...)`
+/// fragment's raw `nSequence` value, per BIP68's type-flag bit.
+fn relative_timelock(sequence: u32) -> TimelockReq {
+    const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+    const SEQUENCE_VALUE_MASK: u32 = 0xFFFF;
+    let units = sequence & SEQUENCE_VALUE_MASK;
+    if sequence & SEQUENCE_TYPE_FLAG != 0 {
+        TimelockReq::AfterPeriod(std::time::Duration::from_secs(units as u64 * 512))
+    } else {
+        TimelockReq::AfterBlocks(units as u16)
+    }
+}
+
+/// Collects every signer public key appearing anywhere in a policy tree.
+fn collect_keys(policy: &SemanticPolicy<PublicKey>, keys: &mut BTreeSet<PublicKey>) {
+    match policy {
+        SemanticPolicy::Key(pk) => {
+            keys.insert(*pk);
+        }
+        SemanticPolicy::Threshold(_, subs) | SemanticPolicy::And(subs) => {
+            subs.iter().for_each(|sub| collect_keys(sub, keys));
+        }
+        SemanticPolicy::Or(subs) => {
+            subs.iter().for_each(|(_, sub)| collect_keys(sub, keys));
+        }
+        _ => {}
+    }
+}
+
+impl WalletTemplate {
+    /// Reconstructs a [`WalletTemplate`] from an existing `wsh(...)` or
+    /// `tr(...)` output descriptor, recovering its `conditions` by walking
+    /// the descriptor's lifted policy: threshold nodes become
+    /// [`SigsReq::All`]/[`SigsReq::Any`]/[`SigsReq::AtLeast`],
+    /// `after`/`older` fragments become the matching absolute/relative
+    /// [`TimelockReq`], and top-level `or` branches become separate
+    /// [`SpendingCondition`]s.
+    ///
+    /// `min_signer_count`/`max_signer_count` are both set to the number of
+    /// distinct signer keys observed across the descriptor; `format` is
+    /// set from whether `descriptor` is segwit v0 or Taproot. The
+    /// descriptor carries no information on which signers are hardware vs.
+    /// watch-only, so `hardware_req`/`watch_only_req` are both set to
+    /// [`Requirement::Allow`], and `network` must be supplied by the
+    /// caller, since a bare (non-extended) public key carries none.
+    pub fn from_descriptor(descriptor: &str, network: PublicNetwork) -> Result<WalletTemplate, DescriptorError> {
+        let descriptor = Descriptor::<PublicKey>::from_str(descriptor)
+            .map_err(|e| DescriptorError::ParseFailure(e.to_string()))?;
+
+        let (format, policy) = match &descriptor {
+            Descriptor::Wsh(wsh) => (
+                WalletFormat::Bip43(Bip43::multisig_descriptor()),
+                wsh.as_inner().lift().map_err(|e| DescriptorError::ParseFailure(e.to_string()))?,
+            ),
+            Descriptor::Tr(tr) => {
+                // Every tapleaf is its own spending condition (the inverse of
+                // `to_taproot_tree`); combine them back into the same `or`
+                // shape a flattened `to_descriptor` policy would have had.
+                let mut leaf_policies = Vec::new();
+                for (_, ms) in tr.iter_scripts() {
+                    leaf_policies.push(ms.lift().map_err(|e| DescriptorError::ParseFailure(e.to_string()))?);
+                }
+                // Compare x-only, not the raw (possibly differently-parity)
+                // compressed key: BIP341 internal keys are inherently x-only,
+                // so two keys differing only in their `02`/`03` prefix are
+                // still the same NUMS point on-chain.
+                let nums_x_only = NUMS_INTERNAL_KEY
+                    .parse::<PublicKey>()
+                    .expect("NUMS_INTERNAL_KEY is a valid compressed public key")
+                    .inner
+                    .x_only_public_key()
+                    .0;
+                let tr_internal_x_only = tr.internal_key().inner.x_only_public_key().0;
+                if tr_internal_x_only != nums_x_only {
+                    leaf_policies.push(SemanticPolicy::Key(*tr.internal_key()));
+                }
+                let policy = if leaf_policies.len() == 1 {
+                    leaf_policies.pop().expect("checked len == 1")
+                } else {
+                    SemanticPolicy::Or(leaf_policies.into_iter().map(|p| (1, p)).collect())
+                };
+                (WalletFormat::Taproot, policy)
+            }
+            _ => return Err(DescriptorError::UnsupportedDescriptor),
+        };
+
+        let mut keys = BTreeSet::new();
+        collect_keys(&policy, &mut keys);
+
+        let conditions = match &policy {
+            SemanticPolicy::Or(branches) => branches
+                .iter()
+                .map(|(_, branch)| condition_from_policy(branch))
+                .collect::<Result<Vec<_>, _>>()?,
+            other => vec![condition_from_policy(other)?],
+        };
+
+        let signer_count = Some(keys.len() as u16);
+        Ok(WalletTemplate {
+            format,
+            min_signer_count: signer_count,
+            max_signer_count: signer_count,
+            hardware_req: Requirement::Allow,
+            watch_only_req: Requirement::Allow,
+            conditions,
+            network,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::{Network, PrivateKey, PublicKey};
+
+    use super::*;
+
+    fn sample_keys(n: u8) -> Vec<PublicKey> {
+        let secp = Secp256k1::new();
+        (1..=n)
+            .map(|i| {
+                let secret = SecretKey::from_slice(&[i; 32]).expect("valid secret key bytes");
+                PrivateKey::new(secret, Network::Bitcoin).public_key(&secp)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn to_descriptor_from_descriptor_round_trip() {
+        let keys = sample_keys(2);
+        let template = WalletTemplate::multisig(
+            PublicNetwork::Testnet,
+            Some(2),
+            TimelockReq::Anytime,
+            TimelockReq::AfterHeight(700_000),
+            Requirement::Allow,
+            Requirement::Allow,
+        );
+        let descriptor = template.to_descriptor(&keys).expect("template compiles to a descriptor");
+
+        let restored = WalletTemplate::from_descriptor(&descriptor.to_string(), PublicNetwork::Testnet)
+            .expect("a compiled descriptor must parse back into a template");
+
+        assert_eq!(restored.format, template.format);
+        assert_eq!(restored.conditions.len(), template.conditions.len());
+        assert!(restored
+            .conditions
+            .iter()
+            .any(|c| c.sigs == SigsReq::All && c.timelock == TimelockReq::Anytime));
+        assert!(restored
+            .conditions
+            .iter()
+            .any(|c| c.sigs == SigsReq::Any && c.timelock == TimelockReq::AfterHeight(700_000)));
+    }
+}
@@ -0,0 +1,245 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime Sarl, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+mod descriptor;
+mod import;
+mod plan;
+mod taproot;
+mod template;
+
+pub use descriptor::{DescriptorError, NUMS_INTERNAL_KEY};
+pub use plan::{plan_spending, Availability, AvailableSigners, SpendingPlan};
+pub use taproot::{TaprootLeaf, TaprootSpendInfo};
+pub use template::{Requirement, WalletTemplate};
+
+/// Threshold separating block-height encoded `nLockTime` values from Unix
+/// timestamp encoded ones, as defined by BIP65 / `OP_CHECKLOCKTIMEVERIFY`.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// `nSequence` bit which, when set, switches BIP68 relative locktime
+/// interpretation from a block count to 512-second intervals.
+const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+
+/// `nSequence` bit which disables BIP68 relative locktime semantics entirely.
+const SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
+
+/// Maximum value encodable in the 16-bit value field of `nSequence` (BIP68).
+const SEQUENCE_VALUE_MASK: u32 = 0xFFFF;
+
+/// Bitcoin networks on which a MyCitadel wallet may operate, excluding
+/// `regtest`, which is only used in integration tests and has no place in a
+/// user-facing wallet template.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum PublicNetwork {
+    Mainnet,
+    Testnet,
+    Signet,
+}
+
+impl Default for PublicNetwork {
+    fn default() -> Self { PublicNetwork::Testnet }
+}
+
+/// BIP43-style derivation purpose schemes used to pick the wallet's output
+/// descriptor shape.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum Bip43 {
+    Bip44,
+    Bip49,
+    Bip84,
+    Bip86,
+    Bip48Native,
+}
+
+impl Default for Bip43 {
+    fn default() -> Self { Bip43::Bip84 }
+}
+
+impl Bip43 {
+    /// Single-signature, segwit v0 (BIP84, `wpkh`).
+    pub fn singlesig_segwit0() -> Bip43 { Bip43::Bip84 }
+
+    /// Single-signature, Taproot (BIP86, `tr`).
+    pub fn singlelsig_taproot() -> Bip43 { Bip43::Bip86 }
+
+    /// Multi-signature, native segwit (BIP48, `wsh`).
+    pub fn multisig_descriptor() -> Bip43 { Bip43::Bip48Native }
+}
+
+/// Output descriptor family a [`WalletTemplate`] should be realized as.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum WalletFormat {
+    /// Legacy or segwit v0 descriptor driven by a BIP43 derivation scheme.
+    Bip43(Bip43),
+    /// Taproot (`tr`) descriptor.
+    Taproot,
+}
+
+impl Default for WalletFormat {
+    fn default() -> Self { WalletFormat::Bip43(Bip43::default()) }
+}
+
+impl From<Bip43> for WalletFormat {
+    fn from(bip43: Bip43) -> Self {
+        match bip43 {
+            Bip43::Bip86 => WalletFormat::Taproot,
+            bip43 => WalletFormat::Bip43(bip43),
+        }
+    }
+}
+
+/// Signature threshold required by a [`SpendingCondition`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum SigsReq {
+    /// All of the wallet's signers must sign.
+    All,
+    /// Any single signer may sign.
+    Any,
+    /// At least the given number of signers must sign.
+    AtLeast(u16),
+}
+
+impl Default for SigsReq {
+    fn default() -> Self { SigsReq::All }
+}
+
+/// Timelock requirement attached to a [`SpendingCondition`].
+///
+/// Bitcoin consensus exposes two independent timelock mechanisms: an
+/// absolute one (`nLockTime`, BIP65) and a relative one (`nSequence`,
+/// BIP68); each of those in turn may be denominated either in blocks or in
+/// calendar time. A [`SpendingCondition`] may use exactly one of the four
+/// combinations below, or none at all.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TimelockReq {
+    /// No timelock: the condition is satisfiable as soon as its signature
+    /// requirement is met.
+    Anytime,
+
+    /// Absolute, calendar-time timelock: `nLockTime` set to a Unix
+    /// timestamp (BIP65, `nLockTime >= 500_000_000`).
+    ///
+    /// Only timestamps that fall within `nLockTime`'s representable range —
+    /// at or after 1985-11-05T00:53:20Z (the BIP65 height/time threshold)
+    /// and no later than 2106-02-07T06:28:15Z (the largest 32-bit Unix
+    /// time) — can actually be
+    /// encoded; [`TimelockReq::as_locktime`] returns `None` for any value
+    /// outside that range rather than silently rewriting it.
+    AfterTime(DateTime<Utc>),
+
+    /// Absolute, block-height timelock: `nLockTime` set to a block height
+    /// (BIP65, `nLockTime < 500_000_000`).
+    AfterHeight(u32),
+
+    /// Relative, block-count timelock: `nSequence` set per BIP68 with the
+    /// type flag clear, counting up to 65535 confirmations.
+    AfterBlocks(u16),
+
+    /// Relative, time-based timelock: `nSequence` set per BIP68 with the
+    /// type flag set, counting 512-second intervals (capped at 0xFFFF
+    /// units, i.e. slightly under 389 days).
+    AfterPeriod(Duration),
+}
+
+impl Default for TimelockReq {
+    fn default() -> Self { TimelockReq::Anytime }
+}
+
+impl TimelockReq {
+    /// Whether this requirement is enforced through the absolute `nLockTime`
+    /// field.
+    pub fn is_absolute(self) -> bool {
+        matches!(self, TimelockReq::AfterTime(_) | TimelockReq::AfterHeight(_))
+    }
+
+    /// Whether this requirement is enforced through the relative `nSequence`
+    /// field (BIP68).
+    pub fn is_relative(self) -> bool {
+        matches!(self, TimelockReq::AfterBlocks(_) | TimelockReq::AfterPeriod(_))
+    }
+
+    /// The `nLockTime` value this requirement demands, if it is absolute.
+    ///
+    /// Returns `None` not only for variants that aren't an absolute
+    /// timelock, but also for an [`TimelockReq::AfterTime`] whose timestamp
+    /// falls outside what `nLockTime` can represent (before the BIP65
+    /// threshold, or beyond a 32-bit Unix time) — the value is never
+    /// clamped or truncated into a different, valid-looking locktime.
+    pub fn as_locktime(self) -> Option<u32> {
+        match self {
+            TimelockReq::AfterHeight(height) => Some(height),
+            TimelockReq::AfterTime(time) => {
+                let secs = time.timestamp();
+                if secs < LOCKTIME_THRESHOLD as i64 || secs > u32::MAX as i64 {
+                    None
+                } else {
+                    Some(secs as u32)
+                }
+            }
+            TimelockReq::Anytime | TimelockReq::AfterBlocks(_) | TimelockReq::AfterPeriod(_) => {
+                None
+            }
+        }
+    }
+
+    /// The `nSequence` value this requirement demands, if it is relative.
+    ///
+    /// Bit 31 (the disable flag) is always left clear; bit 22 (the type
+    /// flag) is set for [`TimelockReq::AfterPeriod`] and left clear for
+    /// [`TimelockReq::AfterBlocks`], as required by BIP68.
+    pub fn as_sequence(self) -> Option<u32> {
+        match self {
+            TimelockReq::AfterBlocks(blocks) => Some(blocks as u32 & !SEQUENCE_DISABLE_FLAG),
+            TimelockReq::AfterPeriod(period) => {
+                let units = (period.as_secs() / 512).min(SEQUENCE_VALUE_MASK as u64) as u32;
+                Some(units | SEQUENCE_TYPE_FLAG)
+            }
+            TimelockReq::Anytime | TimelockReq::AfterTime(_) | TimelockReq::AfterHeight(_) => {
+                None
+            }
+        }
+    }
+}
+
+/// A single spending path of a wallet: a signature threshold, optionally
+/// gated behind a timelock.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct SpendingCondition {
+    pub sigs: SigsReq,
+    pub timelock: TimelockReq,
+}
+
+/// Error returned when a [`WalletTemplate`]'s spending conditions cannot be
+/// represented by Bitcoin's locktime fields.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TemplateError {
+    /// the template mixes block-height and calendar-time values within the
+    /// same (absolute or relative) locktime field.
+    MixedTimelockUnit,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::MixedTimelockUnit => f.write_str(
+                "wallet template mixes block-height and calendar-time timelocks within the same \
+                 locktime field, which consensus rules do not allow",
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
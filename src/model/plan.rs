@@ -0,0 +1,235 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime Sarl, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Spending-plan computation: given the signers presently at hand and the
+//! current chain tip, work out which of a wallet's [`SpendingCondition`]s
+//! can be satisfied right now, and what satisfying each of them costs.
+
+use chrono::{DateTime, Utc};
+
+use super::{Requirement, SigsReq, SpendingCondition, TimelockReq, WalletTemplate};
+
+/// Roughly how many witness bytes (weight units, as witness data counts one
+/// unit per byte) a single ECDSA signature push adds to a witness
+/// (71-72 byte DER signature + sighash byte + length prefix).
+const ECDSA_SIG_WEIGHT: usize = 73;
+
+/// Roughly how many witness bytes (weight units) a single Schnorr (Taproot)
+/// signature push adds to a witness (64-byte signature + length prefix,
+/// default sighash).
+const SCHNORR_SIG_WEIGHT: usize = 65;
+
+/// Signers currently at hand to satisfy a spend, split by how they were
+/// classified when the wallet was set up (see [`Requirement`] on
+/// [`WalletTemplate::hardware_req`]/[`WalletTemplate::watch_only_req`]).
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct AvailableSigners {
+    pub hardware: u16,
+    pub watch_only: u16,
+}
+
+impl AvailableSigners {
+    /// Total number of signers available right now, honoring the
+    /// template's requirement on each signer kind: a kind that is
+    /// [`Requirement::Deny`] for this template cannot contribute signatures
+    /// to it, even if physically present.
+    pub fn usable_count(&self, template: &WalletTemplate) -> u16 {
+        let hardware = match template.hardware_req {
+            Requirement::Deny => 0,
+            Requirement::Allow | Requirement::Require => self.hardware,
+        };
+        let watch_only = match template.watch_only_req {
+            Requirement::Deny => 0,
+            Requirement::Allow | Requirement::Require => self.watch_only,
+        };
+        hardware + watch_only
+    }
+}
+
+/// When a [`SpendingCondition`] can be satisfied.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Availability {
+    /// The condition is satisfiable with the signers at hand right now.
+    Now,
+    /// The condition's timelock has not yet matured; it becomes available
+    /// once the chain reaches this block height.
+    AtHeight(u32),
+    /// The condition's timelock has not yet matured; it becomes available
+    /// once this calendar time passes.
+    AtTime(DateTime<Utc>),
+    /// Not enough signers are currently available to meet the condition's
+    /// signature threshold, regardless of its timelock.
+    MissingSigners { have: u16, need: u16 },
+}
+
+/// A [`SpendingCondition`] together with what satisfying it requires of a
+/// transaction right now: the signature count, the `nLockTime`/`nSequence`
+/// fields that must be set, and a rough witness-size estimate for fee
+/// calculation. Mirrors the `absolute_timelock`/`relative_timelock` fields
+/// miniscript attaches to a `Satisfaction`, so a caller can lift these
+/// straight into a PSBT.
+#[derive(Clone, Debug)]
+pub struct SpendingPlan {
+    pub condition: SpendingCondition,
+    pub signatures_required: u16,
+    pub absolute_timelock: Option<u32>,
+    pub relative_timelock: Option<u32>,
+    pub witness_weight_estimate: usize,
+    pub availability: Availability,
+}
+
+impl SpendingPlan {
+    /// Whether this condition can be satisfied with the signers at hand
+    /// right now, at the current chain height/time.
+    pub fn is_satisfiable_now(&self) -> bool { matches!(self.availability, Availability::Now) }
+}
+
+/// `configured_count` is the wallet's actual, configured total signer
+/// count (as opposed to `available`, how many of those signers are at hand
+/// right now) — `SigsReq::All` must resolve against it, or a condition
+/// requiring all N signers would always look satisfiable once at least one
+/// signer is present.
+fn signatures_required(sigs: SigsReq, configured_count: u16) -> u16 {
+    match sigs {
+        SigsReq::All => configured_count,
+        SigsReq::Any => 1,
+        SigsReq::AtLeast(k) => k,
+    }
+}
+
+fn witness_weight_estimate(template: &WalletTemplate, signatures: u16) -> usize {
+    let per_sig = match template.format {
+        super::WalletFormat::Taproot => SCHNORR_SIG_WEIGHT,
+        super::WalletFormat::Bip43(_) => ECDSA_SIG_WEIGHT,
+    };
+    signatures as usize * per_sig
+}
+
+/// Computes a [`SpendingPlan`] for every condition in `template`, given the
+/// signers currently available and the current chain tip.
+///
+/// A condition whose signature threshold cannot be met with the signers at
+/// hand is reported as [`Availability::MissingSigners`] regardless of its
+/// timelock. Otherwise, a condition whose absolute [`TimelockReq`] has not
+/// yet matured is reported as available at the height/time it matures; one
+/// that has already matured (or carries no timelock) is reported as
+/// [`Availability::Now`]. Relative timelocks ([`TimelockReq::AfterBlocks`],
+/// [`TimelockReq::AfterPeriod`]) are always reported as
+/// [`Availability::Now`]: their maturity is not checked here.
+pub fn plan_spending(
+    template: &WalletTemplate,
+    signers: AvailableSigners,
+    current_height: u32,
+    current_time: DateTime<Utc>,
+) -> Vec<SpendingPlan> {
+    let available = signers.usable_count(template);
+    // The wallet's actual signer count, as fixed when the template was set
+    // up — not how many of those signers happen to be at hand right now.
+    let configured_count = template.max_signer_count.or(template.min_signer_count).unwrap_or(available);
+    template
+        .conditions
+        .iter()
+        .map(|condition| {
+            let signatures_required = signatures_required(condition.sigs, configured_count);
+            let absolute_timelock = condition.timelock.as_locktime();
+            let relative_timelock = condition.timelock.as_sequence();
+            let witness_weight_estimate = witness_weight_estimate(template, signatures_required);
+
+            let availability = if signatures_required > available {
+                Availability::MissingSigners { have: available, need: signatures_required }
+            } else {
+                match condition.timelock {
+                    TimelockReq::Anytime => Availability::Now,
+                    TimelockReq::AfterHeight(height) => {
+                        if current_height >= height {
+                            Availability::Now
+                        } else {
+                            Availability::AtHeight(height)
+                        }
+                    }
+                    TimelockReq::AfterTime(time) => {
+                        if current_time >= time {
+                            Availability::Now
+                        } else {
+                            Availability::AtTime(time)
+                        }
+                    }
+                    // Relative timelocks only start counting once the coin being
+                    // spent confirms, so maturity can't be judged from the
+                    // template alone; report them as available and let the
+                    // caller check the relevant input's confirmation age.
+                    TimelockReq::AfterBlocks(_) | TimelockReq::AfterPeriod(_) => Availability::Now,
+                }
+            };
+
+            SpendingPlan {
+                condition: *condition,
+                signatures_required,
+                absolute_timelock,
+                relative_timelock,
+                witness_weight_estimate,
+                availability,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Bip43, PublicNetwork, WalletFormat};
+
+    fn template() -> WalletTemplate {
+        WalletTemplate {
+            format: WalletFormat::Bip43(Bip43::multisig_descriptor()),
+            min_signer_count: Some(3),
+            max_signer_count: Some(3),
+            hardware_req: Requirement::Allow,
+            watch_only_req: Requirement::Allow,
+            conditions: vec![
+                SpendingCondition { sigs: SigsReq::All, timelock: TimelockReq::Anytime },
+                SpendingCondition { sigs: SigsReq::Any, timelock: TimelockReq::AfterHeight(700_000) },
+            ],
+            network: PublicNetwork::Testnet,
+        }
+    }
+
+    #[test]
+    fn satisfiable_condition_is_now() {
+        let template = template();
+        let signers = AvailableSigners { hardware: 3, watch_only: 0 };
+        let plans = plan_spending(&template, signers, 800_000, Utc::now());
+
+        assert_eq!(plans[0].availability, Availability::Now);
+        assert_eq!(plans[1].availability, Availability::Now);
+    }
+
+    #[test]
+    fn immature_timelock_reports_at_height() {
+        let template = template();
+        let signers = AvailableSigners { hardware: 3, watch_only: 0 };
+        let plans = plan_spending(&template, signers, 500_000, Utc::now());
+
+        assert_eq!(plans[1].availability, Availability::AtHeight(700_000));
+    }
+
+    #[test]
+    fn missing_signers_reports_have_and_need() {
+        let template = template();
+        // Only one signer at hand: not enough for the all-3-signers condition,
+        // but plenty for the any-single-signer recovery condition.
+        let signers = AvailableSigners { hardware: 1, watch_only: 0 };
+        let plans = plan_spending(&template, signers, 800_000, Utc::now());
+
+        assert_eq!(plans[0].availability, Availability::MissingSigners { have: 1, need: 3 });
+        assert_eq!(plans[1].availability, Availability::Now);
+    }
+}
@@ -0,0 +1,179 @@
+// MyCitadel desktop wallet: bitcoin & RGB wallet based on GTK framework.
+//
+// Written in 2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoraprime.ch>
+//
+// Copyright (C) 2022 by Pandora Prime Sarl, Switzerland.
+//
+// This software is distributed without any warranty. You should have received
+// a copy of the AGPL-3.0 License along with this software. If not, see
+// <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
+
+//! Per-condition Taproot script tree construction for [`WalletFormat::Taproot`]
+//! templates: unlike [`super::descriptor::WalletTemplate::to_descriptor`],
+//! which flattens all conditions into a single script, this module gives
+//! each [`SpendingCondition`] its own tapleaf, so that spending a
+//! low-frequency recovery path does not reveal the other conditions
+//! on-chain.
+
+use std::sync::Arc;
+
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TaprootSpendInfo as BitcoinTaprootSpendInfo};
+use bitcoin::{PublicKey, ScriptBuf, XOnlyPublicKey};
+use miniscript::descriptor::TapTree;
+use miniscript::{Miniscript, Tap};
+
+use super::descriptor::{DescriptorError, NUMS_INTERNAL_KEY};
+use super::{SpendingCondition, WalletFormat, WalletTemplate};
+
+/// A single tapleaf of a [`TaprootSpendInfo`], tying a [`SpendingCondition`]
+/// back to the script and depth it was realized at.
+#[derive(Clone, Debug)]
+pub struct TaprootLeaf {
+    /// The spending condition this tapleaf satisfies.
+    pub condition: SpendingCondition,
+    /// The tapscript realizing `condition`.
+    pub script: ScriptBuf,
+    pub leaf_version: LeafVersion,
+    /// Depth of this leaf in the script tree; the all-signers, anytime
+    /// condition is kept near the root (depth 1) while timelocked recovery
+    /// conditions are pushed deeper.
+    pub depth: u8,
+}
+
+/// The Taproot script tree realizing a [`WalletTemplate`]'s conditions, one
+/// tapleaf per condition, together with the per-leaf control-block/merkle
+/// data the signing UI needs to show which leaf a given spend uses.
+pub struct TaprootSpendInfo {
+    pub internal_key: XOnlyPublicKey,
+    pub tap_tree: TapTree<PublicKey>,
+    pub leaves: Vec<TaprootLeaf>,
+    spend_info: BitcoinTaprootSpendInfo,
+}
+
+impl TaprootSpendInfo {
+    /// The merkle control block needed to spend through `leaf`, as required
+    /// in the witness of a script-path Taproot spend.
+    pub fn control_block(&self, leaf: &TaprootLeaf) -> Option<ControlBlock> {
+        self.spend_info
+            .control_block(&(leaf.script.clone(), leaf.leaf_version))
+    }
+
+    /// The output key (`scriptPubKey` `tr()` key) committing to this tree.
+    pub fn output_key(&self) -> XOnlyPublicKey { self.spend_info.output_key().to_inner() }
+}
+
+impl WalletTemplate {
+    /// Builds a Taproot script tree with one tapleaf per spending
+    /// condition.
+    ///
+    /// The first condition (by convention the all-signers, anytime one
+    /// produced by [`WalletTemplate::hodling`]/[`WalletTemplate::multisig`])
+    /// is placed closest to the root; later, timelocked recovery conditions
+    /// are nested progressively deeper, so that revealing a recovery leaf
+    /// on-chain does not also reveal the sibling conditions. The internal
+    /// key is an unspendable NUMS point: every condition is realized as a
+    /// script-path spend, keeping which leaf was used visible only once
+    /// that leaf is actually spent.
+    pub fn to_taproot_tree(
+        &self,
+        signers: &[PublicKey],
+    ) -> Result<TaprootSpendInfo, DescriptorError> {
+        if !matches!(self.format, WalletFormat::Taproot) {
+            return Err(DescriptorError::Compilation(
+                "to_taproot_tree is only valid for WalletFormat::Taproot templates".to_string(),
+            ));
+        }
+        if self.conditions.is_empty() {
+            return Err(DescriptorError::NoConditions);
+        }
+        let min = self.min_signer_count.unwrap_or(1) as usize;
+        let max = self.max_signer_count.map(|m| m as usize).unwrap_or(signers.len());
+        if signers.len() < min || signers.len() > max {
+            return Err(DescriptorError::SignerCountMismatch);
+        }
+
+        let mut leaves = Vec::with_capacity(self.conditions.len());
+        for condition in &self.conditions {
+            let sigs_policy = Self::sigs_policy(condition.sigs, signers);
+            let policy = Self::timelocked_policy(sigs_policy, condition.timelock)?;
+            let ms: Miniscript<PublicKey, Tap> = policy
+                .compile()
+                .map_err(|e| DescriptorError::Compilation(e.to_string()))?;
+            leaves.push((condition.clone(), ms));
+        }
+
+        // Nest leaves left-to-right so that the first (most-likely) condition
+        // sits at depth 1 and each subsequent, less-likely condition is pushed
+        // one level deeper than the last, except the final condition, which
+        // shares the deepest level with the one before it. A lone condition
+        // is the root itself, at depth 0.
+        let mut tree = None;
+        for (_, ms) in leaves.iter().rev() {
+            let leaf = TapTree::Leaf(Arc::new(ms.clone()));
+            tree = Some(match tree {
+                None => leaf,
+                Some(rest) => TapTree::combine(leaf, rest),
+            });
+        }
+        let tap_tree = tree.expect("at least one condition checked above");
+        let mut depths = Vec::with_capacity(leaves.len());
+        collect_leaf_depths(&tap_tree, 0, &mut depths);
+
+        let internal_key: XOnlyPublicKey = NUMS_INTERNAL_KEY
+            .parse::<PublicKey>()
+            .expect("NUMS_INTERNAL_KEY is a valid compressed public key")
+            .inner
+            .x_only_public_key()
+            .0;
+
+        let secp = Secp256k1::verification_only();
+        let spend_info = build_taproot_spend_info(&secp, internal_key, &tap_tree);
+
+        let taproot_leaves = leaves
+            .into_iter()
+            .zip(depths)
+            .map(|((condition, ms), depth)| TaprootLeaf {
+                condition,
+                script: ms.encode(),
+                leaf_version: LeafVersion::TapScript,
+                depth,
+            })
+            .collect();
+
+        Ok(TaprootSpendInfo {
+            internal_key,
+            tap_tree,
+            leaves: taproot_leaves,
+            spend_info,
+        })
+    }
+}
+
+/// Walks a `TapTree`, recording each leaf's actual depth in left-to-right
+/// order, matching the order leaves were folded into the tree in
+/// [`WalletTemplate::to_taproot_tree`]. Reading the depth back off the tree
+/// itself (rather than recomputing it positionally) keeps this correct
+/// regardless of how the tree happens to be shaped.
+fn collect_leaf_depths(tree: &TapTree<PublicKey>, depth: u8, depths: &mut Vec<u8>) {
+    match tree {
+        TapTree::Leaf(_) => depths.push(depth),
+        TapTree::Tree(left, right) => {
+            collect_leaf_depths(left, depth + 1, depths);
+            collect_leaf_depths(right, depth + 1, depths);
+        }
+    }
+}
+
+fn build_taproot_spend_info<C: Verification>(
+    secp: &Secp256k1<C>,
+    internal_key: XOnlyPublicKey,
+    tap_tree: &TapTree<PublicKey>,
+) -> BitcoinTaprootSpendInfo {
+    tap_tree
+        .clone()
+        .into_builder()
+        .finalize(secp, internal_key)
+        .expect("a tree built from compiled miniscript leaves is always a valid Taproot tree")
+}
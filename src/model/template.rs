@@ -9,9 +9,7 @@
 // a copy of the AGPL-3.0 License along with this software. If not, see
 // <https://www.gnu.org/licenses/agpl-3.0-standalone.html>.
 
-use chrono::prelude::*;
-
-use super::{Bip43, PublicNetwork, SpendingCondition, WalletFormat};
+use super::{Bip43, PublicNetwork, SpendingCondition, TemplateError, WalletFormat};
 use crate::model::{SigsReq, TimelockReq};
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -42,6 +40,44 @@ pub struct WalletTemplate {
 }
 
 impl WalletTemplate {
+    /// Checks that the template's spending conditions can be represented by
+    /// Bitcoin's locktime fields.
+    ///
+    /// Each [`SpendingCondition`] is its own `or` branch (see
+    /// [`WalletTemplate::to_policy`] and [`WalletTemplate::to_taproot_tree`]):
+    /// only one branch is ever exercised per spend, and that branch alone
+    /// picks the transaction's `nLockTime`/`nSequence`, so mixing an
+    /// absolute-timelocked condition with a relative-timelocked one across
+    /// different conditions is perfectly valid (e.g. a primary spending
+    /// path alongside a relative-timelocked recovery path). What consensus
+    /// rules do forbid is a *single* locktime field trying to mean two
+    /// things at once, so this only rejects a template that mixes
+    /// block-height and calendar-time values within the same field:
+    /// `nLockTime` (`AfterHeight` vs `AfterTime`) or `nSequence`
+    /// (`AfterBlocks` vs `AfterPeriod`).
+    pub fn validate(&self) -> Result<(), TemplateError> {
+        let mut height = false;
+        let mut time = false;
+        let mut rel_blocks = false;
+        let mut rel_period = false;
+        for condition in &self.conditions {
+            match condition.timelock {
+                TimelockReq::Anytime => {}
+                TimelockReq::AfterHeight(_) => height = true,
+                TimelockReq::AfterTime(_) => time = true,
+                TimelockReq::AfterBlocks(_) => rel_blocks = true,
+                TimelockReq::AfterPeriod(_) => rel_period = true,
+            }
+        }
+        if height && time {
+            return Err(TemplateError::MixedTimelockUnit);
+        }
+        if rel_blocks && rel_period {
+            return Err(TemplateError::MixedTimelockUnit);
+        }
+        Ok(())
+    }
+
     pub fn singlesig(
         taproot: bool,
         network: PublicNetwork,
@@ -73,14 +109,18 @@ impl WalletTemplate {
 
     /// # Panics
     ///
-    /// If `sigs_required` is less than 3.
+    /// If `sigs_required` is less than 3. `recovery_timelock` may be any
+    /// [`TimelockReq`], absolute or relative — e.g. "any single signer
+    /// after 52560 blocks" via [`TimelockReq::AfterBlocks`] — since
+    /// `hodling` only ever produces one timelocked condition, so
+    /// [`WalletTemplate::validate`] can never observe a mix.
     pub fn hodling(
         network: PublicNetwork,
         sigs_required: u16,
+        recovery_timelock: TimelockReq,
         hardware_req: Requirement,
         watch_only_req: Requirement,
     ) -> WalletTemplate {
-        let now = Utc::now();
         if sigs_required < 3 {
             unreachable!("WalletTemplate::hodling must require at least 3 signers")
         }
@@ -91,10 +131,10 @@ impl WalletTemplate {
             },
             SpendingCondition {
                 sigs: SigsReq::Any,
-                timelock: TimelockReq::AfterTime(now.with_year(now.year() + 5).unwrap()),
+                timelock: recovery_timelock,
             },
         ];
-        WalletTemplate {
+        let template = WalletTemplate {
             format: Bip43::multisig_descriptor().into(),
             min_signer_count: Some(sigs_required),
             max_signer_count: None,
@@ -102,19 +142,36 @@ impl WalletTemplate {
             watch_only_req,
             conditions,
             network,
-        }
+        };
+        template
+            .validate()
+            .expect("WalletTemplate::hodling built an inconsistent set of timelocks");
+        template
     }
 
+    /// `mid_timelock` is only used for `sigs_required` of `Some(n)` with
+    /// `n >= 4`, which get a third, intermediate-threshold condition; it is
+    /// silently ignored for `None`/`Some(2)`/`Some(3)`, which only ever
+    /// produce `recovery_timelock`'s condition.
+    ///
     /// # Panics
     ///
-    /// If `sigs_required` is `Some(0)` or `Some(1)`.
+    /// If `sigs_required` is `Some(0)` or `Some(1)`, or if `mid_timelock`
+    /// and `recovery_timelock` use the same locktime field but disagree on
+    /// its unit (see [`WalletTemplate::validate`]) — e.g. one is
+    /// height-based and the other calendar-time-based, or one counts
+    /// blocks and the other a time period. Mixing an absolute-timelocked
+    /// condition with a relative-timelocked one is fine: they are
+    /// independent spending paths, each setting its own locktime field
+    /// when used.
     pub fn multisig(
         network: PublicNetwork,
         sigs_required: Option<u16>,
+        mid_timelock: TimelockReq,
+        recovery_timelock: TimelockReq,
         hardware_req: Requirement,
         watch_only_req: Requirement,
     ) -> WalletTemplate {
-        let now = Utc::now();
         let conditions = match sigs_required {
             None => vec![SpendingCondition::default()],
             Some(0) | Some(1) => unreachable!("WalletTemplate::multisig must expect > 1 signature"),
@@ -125,7 +182,7 @@ impl WalletTemplate {
                 },
                 SpendingCondition {
                     sigs: SigsReq::Any,
-                    timelock: TimelockReq::AfterTime(now.with_year(now.year() + 5).unwrap()),
+                    timelock: recovery_timelock,
                 },
             ],
             Some(3) => vec![
@@ -135,7 +192,7 @@ impl WalletTemplate {
                 },
                 SpendingCondition {
                     sigs: SigsReq::Any,
-                    timelock: TimelockReq::AfterTime(now.with_year(now.year() + 5).unwrap()),
+                    timelock: recovery_timelock,
                 },
             ],
             Some(count) => vec![
@@ -145,15 +202,15 @@ impl WalletTemplate {
                 },
                 SpendingCondition {
                     sigs: SigsReq::AtLeast(count / 2 + count % 2),
-                    timelock: TimelockReq::AfterTime(now.with_year(now.year() + 3).unwrap()),
+                    timelock: mid_timelock,
                 },
                 SpendingCondition {
                     sigs: SigsReq::Any,
-                    timelock: TimelockReq::AfterTime(now.with_year(now.year() + 5).unwrap()),
+                    timelock: recovery_timelock,
                 },
             ],
         };
-        WalletTemplate {
+        let template = WalletTemplate {
             format: Bip43::multisig_descriptor().into(),
             min_signer_count: sigs_required.or(Some(2)),
             max_signer_count: None,
@@ -161,6 +218,10 @@ impl WalletTemplate {
             watch_only_req,
             conditions,
             network,
-        }
+        };
+        template
+            .validate()
+            .expect("WalletTemplate::multisig built an inconsistent set of timelocks");
+        template
     }
 }
\ No newline at end of file